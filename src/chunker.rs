@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+
+// target ~8 KiB chunks: cut whenever the low 13 bits of the rolling hash are
+// zero, which happens on average every 2^13 bytes
+const AVG_CHUNK_SIZE: usize = 8 * 1024;
+const MIN_CHUNK_SIZE: usize = AVG_CHUNK_SIZE / 4;
+const MAX_CHUNK_SIZE: usize = AVG_CHUNK_SIZE * 4;
+const CUT_MASK: u64 = (AVG_CHUNK_SIZE as u64) - 1;
+
+// fixed seed so the table (and therefore the chunk boundaries) are
+// deterministic across runs
+const GEAR_SEED: u64 = 0x9E3779B97F4A7C15;
+
+fn gear_table() -> [u64; 256] {
+  let mut table = [0u64; 256];
+  let mut state = GEAR_SEED;
+  for entry in table.iter_mut() {
+    // successive xorshift64 steps from the fixed seed fill a pseudo-random table
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    *entry = state;
+  }
+  table
+}
+
+// splits `data` into content-defined chunks using a Gear rolling hash: the
+// cut point depends only on the local byte window, so inserting or deleting
+// bytes elsewhere in the stream doesn't shift unrelated chunk boundaries
+pub fn chunk(data: &[u8]) -> Vec<&[u8]> {
+  let table = gear_table();
+  let mut chunks = Vec::new();
+  let mut start = 0;
+  let mut hash: u64 = 0;
+
+  for i in 0..data.len() {
+    hash = (hash << 1).wrapping_add(table[data[i] as usize]);
+    let size = i - start + 1;
+    if (size >= MIN_CHUNK_SIZE && hash & CUT_MASK == 0) || size >= MAX_CHUNK_SIZE {
+      chunks.push(&data[start..=i]);
+      start = i + 1;
+      hash = 0;
+    }
+  }
+  if start < data.len() {
+    chunks.push(&data[start..]);
+  }
+  chunks
+}
+
+fn fast_hash(data: &[u8]) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  data.hash(&mut hasher);
+  hasher.finish()
+}
+
+pub struct DedupStats {
+  pub chunk_count: usize,
+  pub avg_size: f64,
+  pub size_stddev: f64,
+  pub dedup_ratio: f64,
+  pub unique_chunks: Vec<Vec<u8>>,
+}
+
+// chunks `data`, then reports how much of it is duplicate content by hashing
+// each chunk and deduplicating identical hashes
+pub fn analyze(data: &[u8]) -> DedupStats {
+  let chunks = chunk(data);
+  let sizes: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+  let chunk_count = chunks.len();
+  let total_bytes: usize = sizes.iter().sum();
+
+  let avg_size = total_bytes as f64 / chunk_count.max(1) as f64;
+  let variance = if chunk_count > 1 {
+    sizes.iter().map(|&size| (size as f64 - avg_size).powi(2)).sum::<f64>() / (chunk_count - 1) as f64
+  } else {
+    0.0
+  };
+  let size_stddev = variance.sqrt();
+
+  let mut seen: HashSet<u64> = HashSet::new();
+  let mut unique_bytes = 0usize;
+  let mut unique_chunks = Vec::new();
+  for c in &chunks {
+    if seen.insert(fast_hash(c)) {
+      unique_bytes += c.len();
+      unique_chunks.push(c.to_vec());
+    }
+  }
+  let dedup_ratio = 1.0 - (unique_bytes as f64 / total_bytes.max(1) as f64);
+
+  DedupStats {
+    chunk_count,
+    avg_size,
+    size_stddev,
+    dedup_ratio,
+    unique_chunks,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // deterministic pseudo-random bytes so the test doesn't depend on an RNG crate
+  fn pseudo_random_bytes(len: usize) -> Vec<u8> {
+    let mut state: u64 = 0x1234_5678_9abc_def0;
+    let mut data = Vec::with_capacity(len);
+    for _ in 0..len {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      data.push((state & 0xff) as u8);
+    }
+    data
+  }
+
+  #[test]
+  fn chunk_sizes_respect_min_max_bounds() {
+    let data = pseudo_random_bytes(500_000);
+    let chunks = chunk(&data);
+    assert!(chunks.len() > 1);
+
+    for (i, c) in chunks.iter().enumerate() {
+      assert!(c.len() <= MAX_CHUNK_SIZE);
+      // the trailing chunk is whatever is left over and isn't forced to MIN_CHUNK_SIZE
+      if i != chunks.len() - 1 {
+        assert!(c.len() >= MIN_CHUNK_SIZE);
+      }
+    }
+  }
+
+  #[test]
+  fn analyze_reports_dedup_savings_for_repeated_content() {
+    let block = pseudo_random_bytes(AVG_CHUNK_SIZE * 2);
+    let mut data = Vec::new();
+    for _ in 0..8 {
+      data.extend_from_slice(&block);
+    }
+
+    let stats = analyze(&data);
+    assert!(stats.dedup_ratio > 0.0);
+  }
+}
@@ -1,9 +1,27 @@
+use std::collections::{HashMap, HashSet};
 use std::io;
 
+// normalized level range exposed on the CLI; each codec maps this onto its
+// own native range so ratio/throughput can be compared on a common scale
+pub const MIN_LEVEL: u32 = 1;
+pub const MAX_LEVEL: u32 = 9;
+
 pub trait Compressor {
-  fn compress(&self, input: &[u8]) -> Vec<u8>;
+  fn compress(&self, input: &[u8], level: u32) -> Vec<u8>;
   fn decompress(&self, input: &[u8]) -> Vec<u8>;
   fn get_name(&self) -> &'static str;
+  // whether `level` has any effect on this codec's compressed output; codecs
+  // that return false ignore the level passed to `compress`
+  fn supports_level(&self) -> bool {
+    true
+  }
+}
+
+// linearly maps a normalized MIN_LEVEL..=MAX_LEVEL level onto a codec's own
+// native [min, max] range
+fn scale_level(level: u32, min: u32, max: u32) -> u32 {
+  let level = level.clamp(MIN_LEVEL, MAX_LEVEL);
+  min + (level - MIN_LEVEL) * (max - min) / (MAX_LEVEL - MIN_LEVEL)
 }
 
 // BROTLI //
@@ -19,9 +37,10 @@ impl Brotli {
 }
 
 impl Compressor for Brotli {
-  fn compress(&self, input: &[u8]) -> Vec<u8> {
+  fn compress(&self, input: &[u8], level: u32) -> Vec<u8> {
     let target = Vec::new();
-    let mut encoder = brotli::CompressorWriter::new(target, 4096, 0, 20);
+    let quality = scale_level(level, 0, 11);
+    let mut encoder = brotli::CompressorWriter::new(target, 4096, quality, 20);
     io::copy(&mut &*input, &mut encoder).unwrap();
     encoder.into_inner()
   }
@@ -49,8 +68,10 @@ impl Gzip {
 }
 
 impl Compressor for Gzip {
-  fn compress(&self, input: &[u8]) -> Vec<u8> {
+  fn compress(&self, input: &[u8], _level: u32) -> Vec<u8> {
     let target = Vec::new();
+    // libflate's deflate encoder doesn't expose a tunable compression level
+    // like flate2/zlib do, so every normalized level produces the same output
     let mut encoder = libflate::gzip::Encoder::new(target).unwrap();
     io::copy(&mut &*input, &mut encoder).unwrap();
     encoder.finish().into_result().unwrap()
@@ -64,6 +85,9 @@ impl Compressor for Gzip {
   fn get_name(&self) -> &'static str {
     self.name
   }
+  fn supports_level(&self) -> bool {
+    false
+  }
 }
 
 // LZ4 //
@@ -79,7 +103,8 @@ impl Lz4 {
 }
 
 impl Compressor for Lz4 {
-  fn compress(&self, input: &[u8]) -> Vec<u8> {
+  fn compress(&self, input: &[u8], _level: u32) -> Vec<u8> {
+    // lz4_flex's block format has no notion of a compression level
     lz4_flex::compress_prepend_size(input)
   }
   fn decompress(&self, input: &[u8]) -> Vec<u8> {
@@ -88,6 +113,9 @@ impl Compressor for Lz4 {
   fn get_name(&self) -> &'static str {
     self.name
   }
+  fn supports_level(&self) -> bool {
+    false
+  }
 }
 
 // SNAPPY //
@@ -103,7 +131,8 @@ impl Snappy {
 }
 
 impl Compressor for Snappy {
-  fn compress(&self, input: &[u8]) -> Vec<u8> {
+  fn compress(&self, input: &[u8], _level: u32) -> Vec<u8> {
+    // snappy's format has no notion of a compression level
     let target = Vec::new();
     let mut encoder = snap::write::FrameEncoder::new(target);
     io::copy(&mut &*input, &mut encoder).unwrap();
@@ -118,6 +147,216 @@ impl Compressor for Snappy {
   fn get_name(&self) -> &'static str {
     self.name
   }
+  fn supports_level(&self) -> bool {
+    false
+  }
+}
+
+// BZIP2 //
+
+pub struct Bzip2 {
+  pub name: &'static str,
+}
+
+impl Bzip2 {
+  pub fn new() -> Bzip2 {
+    Bzip2 { name: "bzip2" }
+  }
+}
+
+impl Compressor for Bzip2 {
+  fn compress(&self, input: &[u8], level: u32) -> Vec<u8> {
+    let target = Vec::new();
+    let level = scale_level(level, 1, 9);
+    let mut encoder = bzip2::write::BzEncoder::new(target, bzip2::Compression::new(level));
+    io::copy(&mut &*input, &mut encoder).unwrap();
+    encoder.finish().unwrap()
+  }
+  fn decompress(&self, input: &[u8]) -> Vec<u8> {
+    let mut decoder = bzip2::read::BzDecoder::new(input);
+    let mut target = Vec::new();
+    io::copy(&mut decoder, &mut target).unwrap();
+    target
+  }
+  fn get_name(&self) -> &'static str {
+    self.name
+  }
+}
+
+// XZ //
+
+pub struct Xz {
+  pub name: &'static str,
+}
+
+impl Xz {
+  pub fn new() -> Xz {
+    Xz { name: "xz" }
+  }
+}
+
+impl Compressor for Xz {
+  fn compress(&self, input: &[u8], level: u32) -> Vec<u8> {
+    let target = Vec::new();
+    let mut encoder = xz2::write::XzEncoder::new(target, scale_level(level, 0, 9));
+    io::copy(&mut &*input, &mut encoder).unwrap();
+    encoder.finish().unwrap()
+  }
+  fn decompress(&self, input: &[u8]) -> Vec<u8> {
+    let mut decoder = xz2::read::XzDecoder::new(input);
+    let mut target = Vec::new();
+    io::copy(&mut decoder, &mut target).unwrap();
+    target
+  }
+  fn get_name(&self) -> &'static str {
+    self.name
+  }
+}
+
+// FSST //
+
+const FSST_MAX_SYMBOL_LEN: usize = 8;
+const FSST_MAX_TABLE_SIZE: usize = 255;
+const FSST_ESCAPE_CODE: u8 = 255;
+const FSST_TRAINING_ROUNDS: usize = 5;
+const FSST_SAMPLE_SIZE: usize = 16 * 1024;
+
+pub struct Fsst {
+  pub name: &'static str,
+}
+
+impl Fsst {
+  pub fn new() -> Fsst {
+    Fsst { name: "fsst" }
+  }
+}
+
+// finds the longest prefix of `data` that is present in `symbols`, falling
+// back to the first raw byte if no symbol (not even a single byte) matches
+fn longest_match<'a>(data: &'a [u8], symbols: &HashSet<Vec<u8>>) -> &'a [u8] {
+  let max_len = FSST_MAX_SYMBOL_LEN.min(data.len());
+  for len in (1..=max_len).rev() {
+    if symbols.contains(&data[..len]) {
+      return &data[..len];
+    }
+  }
+  &data[..1]
+}
+
+// greedily trains a static symbol table on a sample of the input: repeatedly
+// longest-match the sample against the current table, score every matched
+// symbol and every pair of adjacent matched symbols by frequency * length,
+// then keep the top 255 as the table for the next round
+fn train_symbol_table(input: &[u8]) -> Vec<Vec<u8>> {
+  let sample = &input[..FSST_SAMPLE_SIZE.min(input.len())];
+  let mut symbols: HashSet<Vec<u8>> = (0u16..=255).map(|b| vec![b as u8]).collect();
+
+  for _ in 0..FSST_TRAINING_ROUNDS {
+    if sample.is_empty() {
+      break;
+    }
+
+    let mut freq: HashMap<Vec<u8>, u64> = HashMap::new();
+    let mut pos = 0;
+    let mut prev: Option<&[u8]> = None;
+    while pos < sample.len() {
+      let matched = longest_match(&sample[pos..], &symbols);
+      *freq.entry(matched.to_vec()).or_insert(0) += 1;
+      if let Some(p) = prev {
+        let mut merged = p.to_vec();
+        merged.extend_from_slice(matched);
+        if merged.len() <= FSST_MAX_SYMBOL_LEN {
+          *freq.entry(merged).or_insert(0) += 1;
+        }
+      }
+      prev = Some(matched);
+      pos += matched.len();
+    }
+
+    let mut ranked: Vec<(Vec<u8>, u64)> = freq.into_iter().collect();
+    ranked.sort_by_key(|(symbol, count)| std::cmp::Reverse(count * symbol.len() as u64));
+    ranked.truncate(FSST_MAX_TABLE_SIZE);
+    symbols = ranked.into_iter().map(|(symbol, _)| symbol).collect();
+  }
+
+  let mut table: Vec<Vec<u8>> = symbols.into_iter().collect();
+  table.sort();
+  table.truncate(FSST_MAX_TABLE_SIZE);
+  table
+}
+
+fn fsst_encode(input: &[u8], table: &[Vec<u8>]) -> Vec<u8> {
+  let symbol_set: HashSet<Vec<u8>> = table.iter().cloned().collect();
+  let code_of: HashMap<&[u8], u8> = table
+    .iter()
+    .enumerate()
+    .map(|(code, symbol)| (symbol.as_slice(), code as u8))
+    .collect();
+
+  let mut out = Vec::new();
+  let mut pos = 0;
+  while pos < input.len() {
+    let matched = longest_match(&input[pos..], &symbol_set);
+    match code_of.get(matched) {
+      Some(&code) => out.push(code),
+      None => {
+        out.push(FSST_ESCAPE_CODE);
+        out.push(input[pos]);
+      }
+    }
+    pos += matched.len();
+  }
+  out
+}
+
+impl Compressor for Fsst {
+  fn compress(&self, input: &[u8], _level: u32) -> Vec<u8> {
+    // the symbol table is trained greedily rather than tuned by level
+    let table = train_symbol_table(input);
+
+    let mut out = Vec::new();
+    out.push(table.len() as u8);
+    for symbol in &table {
+      out.push(symbol.len() as u8);
+      out.extend_from_slice(symbol);
+    }
+    out.extend(fsst_encode(input, &table));
+    out
+  }
+
+  fn decompress(&self, input: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let count = input[pos] as usize;
+    pos += 1;
+
+    let mut table: Vec<Vec<u8>> = Vec::with_capacity(count);
+    for _ in 0..count {
+      let len = input[pos] as usize;
+      pos += 1;
+      table.push(input[pos..pos + len].to_vec());
+      pos += len;
+    }
+
+    let mut out = Vec::new();
+    while pos < input.len() {
+      let code = input[pos];
+      pos += 1;
+      if code == FSST_ESCAPE_CODE {
+        out.push(input[pos]);
+        pos += 1;
+      } else {
+        out.extend_from_slice(&table[code as usize]);
+      }
+    }
+    out
+  }
+
+  fn get_name(&self) -> &'static str {
+    self.name
+  }
+  fn supports_level(&self) -> bool {
+    false
+  }
 }
 
 // ZSTD //
@@ -133,10 +372,9 @@ impl Zstd {
 }
 
 impl Compressor for Zstd {
-  fn compress(&self, input: &[u8]) -> Vec<u8> {
+  fn compress(&self, input: &[u8], level: u32) -> Vec<u8> {
     let target = Vec::new();
-    // while level 3 is the default, level 1 seems more fair for this comparison
-    let mut encoder = zstd::stream::Encoder::new(target, 1).unwrap();
+    let mut encoder = zstd::stream::Encoder::new(target, scale_level(level, 1, 22) as i32).unwrap();
     io::copy(&mut &*input, &mut encoder).unwrap();
     encoder.finish().unwrap()
   }
@@ -149,3 +387,53 @@ impl Compressor for Zstd {
     self.name
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bzip2_round_trips() {
+    let bzip2 = Bzip2::new();
+    let input = "the quick brown fox jumps over the lazy dog.".repeat(20);
+    let compressed = bzip2.compress(input.as_bytes(), MIN_LEVEL);
+    let decompressed = bzip2.decompress(&compressed);
+    assert_eq!(decompressed, input.as_bytes());
+  }
+
+  #[test]
+  fn xz_round_trips() {
+    let xz = Xz::new();
+    let input = "the quick brown fox jumps over the lazy dog.".repeat(20);
+    let compressed = xz.compress(input.as_bytes(), MIN_LEVEL);
+    let decompressed = xz.decompress(&compressed);
+    assert_eq!(decompressed, input.as_bytes());
+  }
+
+  #[test]
+  fn fsst_round_trips_repeated_text() {
+    let fsst = Fsst::new();
+    let input = "the quick brown fox jumps over the lazy dog. \
+                 the quick brown fox jumps over the lazy dog again.".repeat(20);
+    let compressed = fsst.compress(input.as_bytes(), MIN_LEVEL);
+    let decompressed = fsst.decompress(&compressed);
+    assert_eq!(decompressed, input.as_bytes());
+  }
+
+  #[test]
+  fn fsst_round_trips_all_byte_values() {
+    let fsst = Fsst::new();
+    let input: Vec<u8> = (0..=255u8).collect();
+    let compressed = fsst.compress(&input, MIN_LEVEL);
+    let decompressed = fsst.decompress(&compressed);
+    assert_eq!(decompressed, input);
+  }
+
+  #[test]
+  fn fsst_round_trips_empty_input() {
+    let fsst = Fsst::new();
+    let compressed = fsst.compress(&[], MIN_LEVEL);
+    let decompressed = fsst.decompress(&compressed);
+    assert_eq!(decompressed, Vec::<u8>::new());
+  }
+}
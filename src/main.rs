@@ -7,16 +7,20 @@ use std::time::Instant;
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
 
+mod chunker;
 mod compressor;
-use crate::compressor::{Brotli, Compressor, Gzip, Lz4, Snappy, Zstd};
+use crate::compressor::{Brotli, Bzip2, Compressor, Fsst, Gzip, Lz4, Snappy, Xz, Zstd, MAX_LEVEL, MIN_LEVEL};
 
 #[derive(clap::ArgEnum, Clone, Debug)]
 enum Algorithm {
   All,
   Brotli,
+  Bzip2,
+  Fsst,
   Gzip,
   Lz4,
   Snappy,
+  Xz,
   Zstd,
 }
 
@@ -25,6 +29,14 @@ enum Operation {
   Benchmark,
   Compress,
   Decompress,
+  Dedup,
+}
+
+#[derive(clap::ArgEnum, Clone, Debug)]
+enum OutputFormat {
+  Table,
+  Csv,
+  Json,
 }
 
 // Compress/Decompress with popular algorithms or compare performance
@@ -45,12 +57,98 @@ struct Args {
   // number of iterations to run for benchmarking
   #[clap(short, long, default_value_t = 25)]
   iterations: u32,
+  // normalized compression level (1-9), mapped onto each algorithm's native range
+  #[clap(short, long, default_value_t = MIN_LEVEL)]
+  level: u32,
+  // in benchmark mode, sweep every level instead of just the one given by --level
+  #[clap(short = 'w', long, action)]
+  sweep_levels: bool,
+  // how to report benchmark results
+  #[clap(arg_enum, short = 'u', long, default_value_t = OutputFormat::Table)]
+  output: OutputFormat,
+  // frame compressed output with a CRC32 of the original bytes (compress/decompress), or
+  // check decompress(compress(x)) == x every iteration and report failures on stderr (benchmark)
+  #[clap(short, long, action)]
+  verify: bool,
 }
 
 fn elapsed_secs(elapsed: Duration) -> f64 {
   elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9
 }
 
+struct Stats {
+  min: f64,
+  max: f64,
+  mean: f64,
+  stddev: f64,
+}
+
+fn compute_stats(samples: &[f64]) -> Stats {
+  let n = samples.len() as f64;
+  let mean = samples.iter().sum::<f64>() / n;
+  let variance = if samples.len() > 1 {
+    samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+  } else {
+    0.0
+  };
+  Stats {
+    min: samples.iter().cloned().fold(f64::INFINITY, f64::min),
+    max: samples.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+    mean,
+    stddev: variance.sqrt(),
+  }
+}
+
+struct BenchmarkResult {
+  name: &'static str,
+  level: u32,
+  ratio: f64,
+  compression: Stats,
+  decompression: Stats,
+}
+
+fn print_csv(results: &[BenchmarkResult]) {
+  println!("algorithm,level,ratio,compression_min,compression_max,compression_mean,compression_stddev,decompression_min,decompression_max,decompression_mean,decompression_stddev");
+  for r in results {
+    println!(
+      "{},{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4}",
+      r.name, r.level, r.ratio,
+      r.compression.min, r.compression.max, r.compression.mean, r.compression.stddev,
+      r.decompression.min, r.decompression.max, r.decompression.mean, r.decompression.stddev,
+    );
+  }
+}
+
+fn print_json(results: &[BenchmarkResult]) {
+  let rows: Vec<String> = results.iter().map(|r| {
+    format!(
+      "{{\"algorithm\":\"{}\",\"level\":{},\"ratio\":{:.4},\"compression\":{{\"min\":{:.4},\"max\":{:.4},\"mean\":{:.4},\"stddev\":{:.4}}},\"decompression\":{{\"min\":{:.4},\"max\":{:.4},\"mean\":{:.4},\"stddev\":{:.4}}}}}",
+      r.name, r.level, r.ratio,
+      r.compression.min, r.compression.max, r.compression.mean, r.compression.stddev,
+      r.decompression.min, r.decompression.max, r.decompression.mean, r.decompression.stddev,
+    )
+  }).collect();
+  println!("[{}]", rows.join(","));
+}
+
+// prepends a CRC32 of `original` (the uncompressed bytes) to `compressed`
+fn frame_with_crc(original: &[u8], compressed: Vec<u8>) -> Vec<u8> {
+  let mut framed = Vec::with_capacity(4 + compressed.len());
+  framed.extend_from_slice(&crc32fast::hash(original).to_le_bytes());
+  framed.extend_from_slice(&compressed);
+  framed
+}
+
+// splits a CRC32-framed buffer into the stored checksum and the remaining
+// payload, or None if the buffer is too short to contain a frame
+fn split_crc_frame(buffer: &[u8]) -> Option<(u32, &[u8])> {
+  if buffer.len() < 4 {
+    return None;
+  }
+  let (crc_bytes, payload) = buffer.split_at(4);
+  Some((u32::from_le_bytes(crc_bytes.try_into().unwrap()), payload))
+}
+
 fn format_size(bytes: usize) -> String {
   if bytes < 1024 {
     format!("{} B", bytes)
@@ -90,71 +188,211 @@ fn main() {
   match args.operation {
     Operation::Benchmark => {
       let size_in_mb = buffer.len() as f64 / 1048576_f64;
-      println!("Running {} iterations of all algorithms on {:?}", args.iterations, args.file);
-      println!("Input size: {}", format_size(buffer.len()));
+      if matches!(args.output, OutputFormat::Table) {
+        println!("Running {} iterations of all algorithms on {:?}", args.iterations, args.file);
+        println!("Input size: {}", format_size(buffer.len()));
+      }
 
       let mut ratio = 100.0;
+      let mut results: Vec<BenchmarkResult> = Vec::new();
 
-      let mut algs: Vec<Box<dyn Compressor>> = Vec::new();
-      algs.push(Box::new(Brotli::new()));
-      algs.push(Box::new(Gzip::new()));
-      algs.push(Box::new(Lz4::new()));
-      algs.push(Box::new(Snappy::new()));
-      algs.push(Box::new(Zstd::new()));
+      let algs: Vec<Box<dyn Compressor>> = vec![
+        Box::new(Brotli::new()),
+        Box::new(Bzip2::new()),
+        Box::new(Fsst::new()),
+        Box::new(Gzip::new()),
+        Box::new(Lz4::new()),
+        Box::new(Snappy::new()),
+        Box::new(Xz::new()),
+        Box::new(Zstd::new()),
+      ];
+
+      let levels: Vec<u32> = if args.sweep_levels {
+        (MIN_LEVEL..=MAX_LEVEL).collect()
+      } else {
+        vec![args.level]
+      };
 
       for alg in algs {
-        println!();
-        let progress = ProgressBar::new(args.iterations as u64);
-        progress.set_style(
-          ProgressStyle::default_bar()
-            .template("{msg} {wide_bar:.cyan/blue} {pos:>7}/{len:7}")
-            .progress_chars("#>-")
-        );
-        progress.set_message(alg.get_name());
-        let mut compression_rate_sum = 0.0;
-        let mut decompression_rate_sum = 0.0;
-
-        for _ in 0..args.iterations {
-          progress.inc(1);
-          let start = Instant::now();
-          let compressed = alg.compress(&buffer);
-          compression_rate_sum += size_in_mb / elapsed_secs(start.elapsed());
-          ratio = buffer.len() as f64 / compressed.len() as f64;
-
-          let start = Instant::now();
-          alg.decompress(&compressed);
-          decompression_rate_sum += size_in_mb / elapsed_secs(start.elapsed());
+        let alg_levels: Vec<u32> = if args.sweep_levels && !alg.supports_level() {
+          eprintln!("{}: level has no effect on this codec, skipping --sweep-levels", alg.get_name());
+          vec![args.level]
+        } else {
+          levels.clone()
+        };
+
+        for &level in &alg_levels {
+          let progress = ProgressBar::new(args.iterations as u64);
+          if matches!(args.output, OutputFormat::Table) {
+            println!();
+            progress.set_style(
+              ProgressStyle::default_bar()
+                .template("{msg} {wide_bar:.cyan/blue} {pos:>7}/{len:7}")
+                .progress_chars("#>-")
+            );
+            progress.set_message(alg.get_name());
+          }
+          let mut compression_rates = Vec::with_capacity(args.iterations as usize);
+          let mut decompression_rates = Vec::with_capacity(args.iterations as usize);
+
+          for _ in 0..args.iterations {
+            progress.inc(1);
+            let start = Instant::now();
+            let compressed = alg.compress(&buffer, level);
+            compression_rates.push(size_in_mb / elapsed_secs(start.elapsed()));
+            ratio = buffer.len() as f64 / compressed.len() as f64;
+
+            let start = Instant::now();
+            let decompressed = alg.decompress(&compressed);
+            decompression_rates.push(size_in_mb / elapsed_secs(start.elapsed()));
+            if args.verify && decompressed != buffer {
+              eprintln!("{} level={} failed round-trip verification", alg.get_name(), level);
+            }
+          }
+          progress.finish_and_clear();
+
+          let compression = compute_stats(&compression_rates);
+          let decompression = compute_stats(&decompression_rates);
+          if matches!(args.output, OutputFormat::Table) {
+            println!(
+              "{} level={} compression: ratio={:.2} rate(min/mean/max/stddev)={:.1}/{:.1}/{:.1}/{:.1} MBps",
+              alg.get_name(), level, ratio, compression.min, compression.mean, compression.max, compression.stddev
+            );
+            println!(
+              "{} level={} decompression: rate(min/mean/max/stddev)={:.1}/{:.1}/{:.1}/{:.1} MBps",
+              alg.get_name(), level, decompression.min, decompression.mean, decompression.max, decompression.stddev
+            );
+          }
+          results.push(BenchmarkResult { name: alg.get_name(), level, ratio, compression, decompression });
         }
-        progress.finish_and_clear();
-        println!("{} compression: ratio={:.2} rate={:.1} MBps", alg.get_name(), ratio, compression_rate_sum / args.iterations as f64);
-        println!("{} decompression: rate={:.1} MBps", alg.get_name(), decompression_rate_sum / args.iterations as f64);
+      }
+
+      match args.output {
+        OutputFormat::Table => {},
+        OutputFormat::Csv => print_csv(&results),
+        OutputFormat::Json => print_json(&results),
       }
     },
     Operation::Compress => {
       let compressed = match args.algorithm {
         Algorithm::All => Vec::new(),
-        Algorithm::Brotli => Brotli::new().compress(&buffer),
-        Algorithm::Gzip => Gzip::new().compress(&buffer),
-        Algorithm::Lz4 => Lz4::new().compress(&buffer),
-        Algorithm::Snappy => Snappy::new().compress(&buffer),
-        Algorithm::Zstd =>  Zstd::new().compress(&buffer),
+        Algorithm::Brotli => Brotli::new().compress(&buffer, args.level),
+        Algorithm::Bzip2 => Bzip2::new().compress(&buffer, args.level),
+        Algorithm::Fsst => Fsst::new().compress(&buffer, args.level),
+        Algorithm::Gzip => Gzip::new().compress(&buffer, args.level),
+        Algorithm::Lz4 => Lz4::new().compress(&buffer, args.level),
+        Algorithm::Snappy => Snappy::new().compress(&buffer, args.level),
+        Algorithm::Xz => Xz::new().compress(&buffer, args.level),
+        Algorithm::Zstd =>  Zstd::new().compress(&buffer, args.level),
+      };
+      let output = if args.verify {
+        frame_with_crc(&buffer, compressed)
+      } else {
+        compressed
       };
       if args.base64 {
-        b64_writer.write_all(&compressed).unwrap();
+        b64_writer.write_all(&output).unwrap();
       } else {
-        io::stdout().write_all(&compressed).unwrap();
+        io::stdout().write_all(&output).unwrap();
       }
     },
     Operation::Decompress => {
+      let (expected_crc, payload) = if args.verify {
+        match split_crc_frame(&buffer) {
+          Some((crc, payload)) => (Some(crc), payload),
+          None => {
+            eprintln!("--verify: input is too short to contain a CRC32 frame");
+            std::process::exit(1);
+          }
+        }
+      } else {
+        (None, &buffer[..])
+      };
       let decompressed = match args.algorithm {
         Algorithm::All => Vec::new(),
-        Algorithm::Brotli => Brotli::new().decompress(&buffer),
-        Algorithm::Gzip => Gzip::new().decompress(&buffer),
-        Algorithm::Lz4 => Lz4::new().decompress(&buffer),
-        Algorithm::Snappy => Snappy::new().decompress(&buffer),
-        Algorithm::Zstd =>  Zstd::new().decompress(&buffer),
+        Algorithm::Brotli => Brotli::new().decompress(payload),
+        Algorithm::Bzip2 => Bzip2::new().decompress(payload),
+        Algorithm::Fsst => Fsst::new().decompress(payload),
+        Algorithm::Gzip => Gzip::new().decompress(payload),
+        Algorithm::Lz4 => Lz4::new().decompress(payload),
+        Algorithm::Snappy => Snappy::new().decompress(payload),
+        Algorithm::Xz => Xz::new().decompress(payload),
+        Algorithm::Zstd =>  Zstd::new().decompress(payload),
       };
+      if let Some(expected) = expected_crc {
+        let actual = crc32fast::hash(&decompressed);
+        if actual != expected {
+          eprintln!("crc32 mismatch: expected {:08x}, got {:08x}", expected, actual);
+          std::process::exit(1);
+        }
+      }
       io::stdout().write_all(&decompressed).unwrap();
     },
+    Operation::Dedup => {
+      let stats = chunker::analyze(&buffer);
+      println!("Input size: {}", format_size(buffer.len()));
+      println!("chunk_count={} avg_size={} stddev={:.1}", stats.chunk_count, format_size(stats.avg_size as usize), stats.size_stddev);
+      println!("dedup_ratio={:.4}", stats.dedup_ratio);
+
+      let deduped: Vec<u8> = stats.unique_chunks.concat();
+      println!();
+      println!("Compressing the {} deduplicated chunks ({})", stats.unique_chunks.len(), format_size(deduped.len()));
+
+      let algs: Vec<Box<dyn Compressor>> = match args.algorithm {
+        Algorithm::All => vec![
+          Box::new(Brotli::new()),
+          Box::new(Bzip2::new()),
+          Box::new(Fsst::new()),
+          Box::new(Gzip::new()),
+          Box::new(Lz4::new()),
+          Box::new(Snappy::new()),
+          Box::new(Xz::new()),
+          Box::new(Zstd::new()),
+        ],
+        Algorithm::Brotli => vec![Box::new(Brotli::new())],
+        Algorithm::Bzip2 => vec![Box::new(Bzip2::new())],
+        Algorithm::Fsst => vec![Box::new(Fsst::new())],
+        Algorithm::Gzip => vec![Box::new(Gzip::new())],
+        Algorithm::Lz4 => vec![Box::new(Lz4::new())],
+        Algorithm::Snappy => vec![Box::new(Snappy::new())],
+        Algorithm::Xz => vec![Box::new(Xz::new())],
+        Algorithm::Zstd => vec![Box::new(Zstd::new())],
+      };
+      for alg in algs {
+        let compressed = alg.compress(&deduped, args.level);
+        let combined_ratio = buffer.len() as f64 / compressed.len() as f64;
+        println!("{}: deduped+compressed ratio={:.2}", alg.get_name(), combined_ratio);
+      }
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn crc_frame_round_trips() {
+    let original = b"hello world";
+    let framed = frame_with_crc(original, b"compressed-bytes".to_vec());
+    let (crc, payload) = split_crc_frame(&framed).unwrap();
+    assert_eq!(payload, b"compressed-bytes");
+    assert_eq!(crc, crc32fast::hash(original));
+  }
+
+  #[test]
+  fn crc_mismatch_is_detected_after_corruption() {
+    let original = b"hello world".to_vec();
+    let framed = frame_with_crc(&original, original.clone());
+    let (expected_crc, payload) = split_crc_frame(&framed).unwrap();
+
+    let mut corrupted = payload.to_vec();
+    corrupted[0] ^= 0xff;
+    assert_ne!(crc32fast::hash(&corrupted), expected_crc);
+  }
+
+  #[test]
+  fn split_crc_frame_rejects_input_shorter_than_a_frame() {
+    assert!(split_crc_frame(&[1, 2, 3]).is_none());
   }
 }